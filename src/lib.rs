@@ -5,8 +5,10 @@ use rand::distributions::{Distribution, Standard};
 
 use std::cell::{Cell, RefCell};
 use std::cmp::{Ordering};
+use std::collections::{BTreeMap};
 use std::collections::hash_map::{RandomState};
 use std::hash::{BuildHasher, Hash, Hasher};
+use std::iter::{FromIterator};
 use std::rc::{Rc};
 
 /// A key-value pair partially ordered only by the key.
@@ -166,6 +168,11 @@ impl<K, V, P> VertreapMap<K, V, P> {
     self.vtreap.len()
   }
 
+  /// The version stamp at which this map was created.
+  pub fn version(&self) -> u64 {
+    self.vtreap.version()
+  }
+
   /// Create an ordered iterator over the key-value pairs in the map.
   ///
   /// The iterator performs an in-order depth-first traversal of the backing
@@ -181,6 +188,17 @@ where K: Ord,
   pub fn find(&self, key: &K) -> Option<Rc<KV<K, V>>> {
     self.vtreap.find(key)
   }
+
+  /// Return the key-value pair with the `idx`-th smallest key, or `None`
+  /// if `idx` is out of bounds.
+  pub fn select(&self, idx: usize) -> Option<Rc<KV<K, V>>> {
+    self.vtreap.select(idx)
+  }
+
+  /// Return the number of keys strictly less than `key`.
+  pub fn rank(&self, key: &K) -> usize {
+    self.vtreap.rank(key)
+  }
 }
 
 impl<K, V, P> VertreapMap<K, V, P>
@@ -199,6 +217,245 @@ where K: Ord,
       vtreap:   new_vtreap,
     }
   }
+
+  /// Remove the key-value pair matching `key`, if present, and return the
+  /// resulting map. The old map is left untouched, sharing structure with
+  /// the new one.
+  pub fn remove(&self, key: &K) -> VertreapMap<K, V, P> {
+    let new_vtreap = self.vtreap.remove_with(key);
+    VertreapMap{
+      state:    self.state.clone(),
+      vtreap:   new_vtreap,
+    }
+  }
+
+  /// Split into the sub-map of keys less than `key`, the matching pair if
+  /// present, and the sub-map of keys greater than `key`.
+  pub fn split(&self, key: &K) -> (VertreapMap<K, V, P>, Option<Rc<KV<K, V>>>, VertreapMap<K, V, P>) {
+    let (left, mid, right) = self.vtreap.split(key);
+    (
+      VertreapMap{state: self.state.clone(), vtreap: left},
+      mid,
+      VertreapMap{state: self.state.clone(), vtreap: right},
+    )
+  }
+
+  /// Split at `key`, attaching any pair equal to `key` to the right half,
+  /// analogous to `BTreeMap::split_off`.
+  pub fn split_off(&self, key: &K) -> (VertreapMap<K, V, P>, VertreapMap<K, V, P>) {
+    let (left, right) = self.vtreap.split_off(key);
+    (
+      VertreapMap{state: self.state.clone(), vtreap: left},
+      VertreapMap{state: self.state.clone(), vtreap: right},
+    )
+  }
+
+  /// Join `self` and `other` into a single map, assuming every key in
+  /// `self` precedes every key in `other`.
+  pub fn join(&self, other: &VertreapMap<K, V, P>) -> VertreapMap<K, V, P> {
+    VertreapMap{
+      state:    self.state.clone(),
+      vtreap:   self.vtreap.join(&other.vtreap),
+    }
+  }
+
+  /// Insert every key-value pair from `iter`, advancing the shared
+  /// version counter only once for the whole batch.
+  pub fn append_all<I: IntoIterator<Item=(K, V)>>(&self, iter: I) -> VertreapMap<K, V, P> {
+    let state = self.state.clone();
+    let items = iter.into_iter().map(|(k, v)| {
+      let priority = state.make_priority(&k);
+      (priority, KV{k, v})
+    });
+    VertreapMap{
+      state:    self.state.clone(),
+      vtreap:   self.vtreap.append_all(items),
+    }
+  }
+}
+
+impl<K, V, P> VertreapMap<K, V, P>
+where K: Ord,
+      P: Copy + Ord,
+      Standard: Distribution<P>,
+{
+  /// Build a map in O(n) from key-value pairs already sorted in
+  /// ascending order by key, generating priorities via `ThreadRng`.
+  pub fn from_sorted_iter<I: IntoIterator<Item=(K, V)>>(iter: I) -> VertreapMap<K, V, P> {
+    let state: Rc<dyn KeyedGenerator<K, P>> = Rc::new(ThreadRngGenerator::default());
+    let items = iter.into_iter().map(|(k, v)| {
+      let priority = state.make_priority(&k);
+      (priority, KV{k, v})
+    });
+    VertreapMap{
+      vtreap:   Vertreap::from_sorted_iter(items),
+      state,
+    }
+  }
+}
+
+impl<K, V, P> FromIterator<(K, V)> for VertreapMap<K, V, P>
+where K: Ord,
+      P: Copy + Ord,
+      Standard: Distribution<P>,
+{
+  /// Builds in O(n); assumes `iter` yields pairs already sorted in
+  /// ascending order by key (see `from_sorted_iter`).
+  fn from_iter<I: IntoIterator<Item=(K, V)>>(iter: I) -> VertreapMap<K, V, P> {
+    VertreapMap::from_sorted_iter(iter)
+  }
+}
+
+/// A registry of committed `VertreapMap` snapshots keyed by version,
+/// allowing time-travel reads of past states from a single handle.
+pub struct VersionedVertreapMap<K, V, P=u64> {
+  snapshots:    Rc<RefCell<BTreeMap<u64, VertreapMap<K, V, P>>>>,
+}
+
+impl<K, V, P> Clone for VersionedVertreapMap<K, V, P> {
+  fn clone(&self) -> VersionedVertreapMap<K, V, P> {
+    VersionedVertreapMap{
+      snapshots:    self.snapshots.clone(),
+    }
+  }
+}
+
+impl<K, V, P> VersionedVertreapMap<K, V, P> {
+  /// Wrap `map` as the first committed snapshot of a new MVCC registry.
+  pub fn new(map: VertreapMap<K, V, P>) -> VersionedVertreapMap<K, V, P> {
+    let mut snapshots = BTreeMap::new();
+    snapshots.insert(map.version(), map);
+    VersionedVertreapMap{
+      snapshots:    Rc::new(RefCell::new(snapshots)),
+    }
+  }
+
+  /// Register `map` as a newly committed snapshot, making it queryable
+  /// by `find_at`/`iter_at`/`len_at` from every handle sharing this
+  /// registry.
+  pub fn commit(&self, map: VertreapMap<K, V, P>) {
+    self.snapshots.borrow_mut().insert(map.version(), map);
+  }
+
+  /// The version of the most recently committed snapshot.
+  pub fn latest_version(&self) -> u64 {
+    *self.snapshots.borrow().keys().next_back().expect("VersionedVertreapMap always has at least one snapshot")
+  }
+
+  /// Drop committed snapshots older than `before`, releasing the `Rc`s
+  /// that are no longer reachable through this registry, but always
+  /// retain the latest snapshot so the registry never becomes empty.
+  pub fn gc(&self, before: u64) {
+    let latest = self.latest_version();
+    self.snapshots.borrow_mut().retain(|&version, _| version >= before || version == latest);
+  }
+}
+
+impl<K, V, P> VersionedVertreapMap<K, V, P>
+where K: Ord,
+{
+  /// Find the key-value pair matching `key` in the snapshot most recently
+  /// committed at or before `version`, if any.
+  pub fn find_at(&self, version: u64, key: &K) -> Option<Rc<KV<K, V>>> {
+    let snapshots = self.snapshots.borrow();
+    snapshots.range(..=version).next_back().and_then(|(_, snapshot)| snapshot.find(key))
+  }
+
+  /// Create an ordered iterator over the key-value pairs in the snapshot
+  /// most recently committed at or before `version`, if any.
+  pub fn iter_at(&self, version: u64) -> Option<VertreapMapIter<K, V, P>> {
+    let snapshots = self.snapshots.borrow();
+    snapshots.range(..=version).next_back().map(|(_, snapshot)| snapshot.iter())
+  }
+
+  /// Count the number of key-value pairs in the snapshot most recently
+  /// committed at or before `version`, or `0` if there is no such
+  /// snapshot.
+  pub fn len_at(&self, version: u64) -> usize {
+    let snapshots = self.snapshots.borrow();
+    snapshots.range(..=version).next_back().map(|(_, snapshot)| snapshot.len()).unwrap_or(0)
+  }
+}
+
+/// An ordered associative map like `VertreapMap`, but ordered by an
+/// explicit runtime comparator `C` instead of requiring `K: Ord`. The
+/// comparator is captured once at construction and carried along in
+/// every derived version, so all clones stay consistently ordered.
+pub struct VertreapMapBy<K, V, C, P=u64> {
+  state:    Rc<dyn KeyedGenerator<K, P>>,
+  cmp:      Rc<C>,
+  vtreap:   Vertreap<KV<K, V>, P>,
+}
+
+impl<K, V, C, P> Clone for VertreapMapBy<K, V, C, P> {
+  fn clone(&self) -> VertreapMapBy<K, V, C, P> {
+    VertreapMapBy{
+      state:    self.state.clone(),
+      cmp:      self.cmp.clone(),
+      vtreap:   self.vtreap.clone(),
+    }
+  }
+}
+
+impl<K, V, C, P> VertreapMapBy<K, V, C, P> where Standard: Distribution<P> {
+  /// Create a new persistent treap-backed map ordered by `cmp`, where
+  /// priorities are generated by `ThreadRng`.
+  pub fn new_with_thread_rng(cmp: C) -> VertreapMapBy<K, V, C, P> {
+    VertreapMapBy{
+      state:    Rc::new(ThreadRngGenerator::default()),
+      cmp:      Rc::new(cmp),
+      vtreap:   Vertreap::default(),
+    }
+  }
+
+  /// Create a new persistent treap-backed map ordered by `cmp`, where
+  /// priorities are generated by a provided `Rng`.
+  pub fn new_with_rng<R: Rng + 'static>(cmp: C, rng: R) -> VertreapMapBy<K, V, C, P> {
+    VertreapMapBy{
+      state:    Rc::new(RngGenerator::new(rng)),
+      cmp:      Rc::new(cmp),
+      vtreap:   Vertreap::default(),
+    }
+  }
+}
+
+impl<K, V, C, P> VertreapMapBy<K, V, C, P> {
+  /// Count the number of key-value pairs in the map.
+  pub fn len(&self) -> usize {
+    self.vtreap.len()
+  }
+
+  /// Create an ordered iterator over the key-value pairs in the map.
+  pub fn iter(&self) -> VertreapMapIter<K, V, P> {
+    VertreapMapIter{inner: self.vtreap.iter()}
+  }
+}
+
+impl<K, V, C, P> VertreapMapBy<K, V, C, P>
+where C: Fn(&K, &K) -> Ordering,
+{
+  pub fn find(&self, key: &K) -> Option<Rc<KV<K, V>>> {
+    self.vtreap.find_by(key, &*self.cmp)
+  }
+}
+
+impl<K, V, C, P> VertreapMapBy<K, V, C, P>
+where C: Fn(&K, &K) -> Ordering,
+      P: Copy + Ord,
+{
+  pub fn append(&self, key: K, val: V) -> VertreapMapBy<K, V, C, P> {
+    let priority = self.state.make_priority(&key);
+    self.append_with_priority(priority, key, val)
+  }
+
+  pub fn append_with_priority(&self, priority: P, key: K, val: V) -> VertreapMapBy<K, V, C, P> {
+    let new_vtreap = self.vtreap.append_with_priority_by(priority, key, val, &*self.cmp);
+    VertreapMapBy{
+      state:    self.state.clone(),
+      cmp:      self.cmp.clone(),
+      vtreap:   new_vtreap,
+    }
+  }
 }
 
 pub struct VertreapSetIter<K, P> {
@@ -262,6 +519,17 @@ where K: Ord,
   pub fn contains(&self, key: &K) -> bool {
     self.vtreap.find(key).is_some()
   }
+
+  /// Return the `idx`-th smallest key in the set, or `None` if `idx` is
+  /// out of bounds.
+  pub fn select(&self, idx: usize) -> Option<Rc<K>> {
+    self.vtreap.select(idx)
+  }
+
+  /// Return the number of keys strictly less than `key`.
+  pub fn rank(&self, key: &K) -> usize {
+    self.vtreap.rank(key)
+  }
 }
 
 impl<K, P> VertreapSet<K, P>
@@ -277,6 +545,44 @@ where K: Ord,
     let priority: P = rng.sample(&Standard);
     self.append_with_priority(priority, key)
   }
+
+  /// Insert every key from `iter`, advancing the shared version counter
+  /// only once for the whole batch.
+  pub fn append_all<I: IntoIterator<Item=K>>(&self, iter: I) -> VertreapSet<K, P> {
+    let mut rng = thread_rng();
+    let items = iter.into_iter().map(|key| {
+      let priority: P = rng.sample(&Standard);
+      (priority, key)
+    });
+    VertreapSet{
+      vtreap:   self.vtreap.append_all(items),
+    }
+  }
+
+  /// Build a set in O(n) from keys already sorted in ascending order,
+  /// generating priorities via `ThreadRng`.
+  pub fn from_sorted_iter<I: IntoIterator<Item=K>>(iter: I) -> VertreapSet<K, P> {
+    let mut rng = thread_rng();
+    let items = iter.into_iter().map(|key| {
+      let priority: P = rng.sample(&Standard);
+      (priority, key)
+    });
+    VertreapSet{
+      vtreap:   Vertreap::from_sorted_iter(items),
+    }
+  }
+}
+
+impl<K, P> FromIterator<K> for VertreapSet<K, P>
+where K: Ord,
+      P: Copy + Ord,
+      Standard: Distribution<P>,
+{
+  /// Builds in O(n); assumes `iter` yields keys already sorted in
+  /// ascending order (see `from_sorted_iter`).
+  fn from_iter<I: IntoIterator<Item=K>>(iter: I) -> VertreapSet<K, P> {
+    VertreapSet::from_sorted_iter(iter)
+  }
 }
 
 impl<K, P> VertreapSet<K, P>
@@ -289,6 +595,44 @@ where K: Ord,
       vtreap:   new_vtreap,
     }
   }
+
+  /// Remove `key` from the set, if present, and return the resulting set.
+  /// The old set is left untouched, sharing structure with the new one.
+  pub fn remove(&self, key: &K) -> VertreapSet<K, P> {
+    let new_vtreap = self.vtreap.remove_with(key);
+    VertreapSet{
+      vtreap:   new_vtreap,
+    }
+  }
+
+  /// Split into the sub-set of keys less than `key`, whether `key` itself
+  /// is present, and the sub-set of keys greater than `key`.
+  pub fn split(&self, key: &K) -> (VertreapSet<K, P>, bool, VertreapSet<K, P>) {
+    let (left, mid, right) = self.vtreap.split(key);
+    (
+      VertreapSet{vtreap: left},
+      mid.is_some(),
+      VertreapSet{vtreap: right},
+    )
+  }
+
+  /// Split at `key`, attaching `key` itself to the right half if present,
+  /// analogous to `BTreeSet::split_off`.
+  pub fn split_off(&self, key: &K) -> (VertreapSet<K, P>, VertreapSet<K, P>) {
+    let (left, right) = self.vtreap.split_off(key);
+    (
+      VertreapSet{vtreap: left},
+      VertreapSet{vtreap: right},
+    )
+  }
+
+  /// Join `self` and `other` into a single set, assuming every key in
+  /// `self` precedes every key in `other`.
+  pub fn join(&self, other: &VertreapSet<K, P>) -> VertreapSet<K, P> {
+    VertreapSet{
+      vtreap:   self.vtreap.join(&other.vtreap),
+    }
+  }
 }
 
 pub struct VertreapIter<Item, P> {
@@ -382,6 +726,11 @@ impl<Item, P> Vertreap<Item, P> {
     self.count
   }
 
+  /// The version stamp at which this tree was created.
+  pub fn version(&self) -> u64 {
+    self.version
+  }
+
   pub fn iter(&self) -> VertreapIter<Item, P> {
     VertreapIter::new(self.root.clone())
   }
@@ -392,6 +741,26 @@ impl<Item, P> Vertreap<Item, P> {
       Some(ref root_node) => root_node._find(self.version, key),
     }
   }
+
+  /// Return the `idx`-th smallest item (0-indexed) in O(log n), or `None`
+  /// if `idx` is out of bounds.
+  pub fn select(&self, idx: usize) -> Option<Rc<Item>> {
+    if idx >= self.count {
+      return None;
+    }
+    match self.root {
+      None => None,
+      Some(ref root_node) => root_node._select(self.version, idx),
+    }
+  }
+
+  /// Return the number of items strictly less than `key`.
+  pub fn rank<K>(&self, key: &K) -> usize where Item: PartialOrd<K> {
+    match self.root {
+      None => 0,
+      Some(ref root_node) => root_node._rank(self.version, key),
+    }
+  }
 }
 
 impl<Item, P> Vertreap<Item, P>
@@ -420,21 +789,204 @@ where Item: PartialOrd,
     };
     new_vtreap
   }
+
+  /// Remove the item matching `key`, if present, and return the resulting
+  /// tree. If no item matches, the returned tree has the same count as
+  /// `self`.
+  pub fn remove_with<K>(&self, key: &K) -> Vertreap<Item, P> where Item: PartialOrd<K> {
+    let old_version = self.state.version.get();
+    let new_version = old_version + 1;
+    assert!(new_version != 0);
+    self.state.version.set(new_version);
+    assert!(self.version < new_version);
+    let (new_root, removed_ct) = match self.root {
+      None => (None, 0),
+      Some(ref root_node) => root_node._remove(new_version, key),
+    };
+    Vertreap{
+      version:    new_version,
+      count:      self.count - removed_ct,
+      state:      self.state.clone(),
+      root:       new_root.map(Rc::new),
+    }
+  }
+
+  /// Split into the sub-treap of items less than `key`, the matching item
+  /// if present, and the sub-treap of items greater than `key`. Both
+  /// returned treaps share structure with `self` and advance the shared
+  /// version counter.
+  pub fn split<K>(&self, key: &K) -> (Vertreap<Item, P>, Option<Rc<Item>>, Vertreap<Item, P>) where Item: PartialOrd<K> {
+    let old_version = self.state.version.get();
+    let new_version = old_version + 1;
+    assert!(new_version != 0);
+    self.state.version.set(new_version);
+    assert!(self.version < new_version);
+    let (left_root, mid, right_root) = match self.root {
+      None => (None, None, None),
+      Some(ref root_node) => root_node._split(new_version, key),
+    };
+    let left_root = left_root.map(Rc::new);
+    let right_root = right_root.map(Rc::new);
+    let left = Vertreap{version: new_version, count: _node_size(&left_root), state: self.state.clone(), root: left_root};
+    let right = Vertreap{version: new_version, count: _node_size(&right_root), state: self.state.clone(), root: right_root};
+    (left, mid.map(|(_, item)| item), right)
+  }
+
+  /// Split at `key`, attaching any item equal to `key` to the right half
+  /// instead of returning it separately, analogous to `BTreeMap::split_off`.
+  pub fn split_off<K>(&self, key: &K) -> (Vertreap<Item, P>, Vertreap<Item, P>) where Item: PartialOrd<K> {
+    let old_version = self.state.version.get();
+    let new_version = old_version + 1;
+    assert!(new_version != 0);
+    self.state.version.set(new_version);
+    assert!(self.version < new_version);
+    let (left_root, mid, right_root) = match self.root {
+      None => (None, None, None),
+      Some(ref root_node) => root_node._split(new_version, key),
+    };
+    let right_root = match mid {
+      None => right_root,
+      Some((priority, item)) => {
+        let mid_node = VertreapNode::branch(new_version, priority, item, None, None);
+        _join_nodes(&Some(Rc::new(mid_node)), &right_root.map(Rc::new), new_version)
+      }
+    };
+    let left_root = left_root.map(Rc::new);
+    let right_root = right_root.map(Rc::new);
+    let left = Vertreap{version: new_version, count: _node_size(&left_root), state: self.state.clone(), root: left_root};
+    let right = Vertreap{version: new_version, count: _node_size(&right_root), state: self.state.clone(), root: right_root};
+    (left, right)
+  }
+
+  /// Join two treaps where every key in `self` precedes every key in
+  /// `other`, preserving heap order by comparing root priorities.
+  ///
+  /// Both inputs' version counters are advanced to `new_version`, so
+  /// lineages joined from otherwise-unrelated `Vertreap`s stay numerically
+  /// distinct from versions already present in the joined tree.
+  pub fn join(&self, other: &Vertreap<Item, P>) -> Vertreap<Item, P> {
+    let old_version = self.state.version.get().max(other.state.version.get());
+    let new_version = old_version + 1;
+    assert!(new_version != 0);
+    self.state.version.set(new_version);
+    other.state.version.set(new_version);
+    assert!(self.version < new_version);
+    assert!(other.version < new_version);
+    let new_root = _join_nodes(&self.root, &other.root, new_version).map(Rc::new);
+    Vertreap{
+      version:    new_version,
+      count:      _node_size(&new_root),
+      state:      self.state.clone(),
+      root:       new_root,
+    }
+  }
+
+  /// Insert every `(priority, item)` pair from `items`, advancing the
+  /// shared version counter only once for the whole batch.
+  pub fn append_all<I: IntoIterator<Item=(P, Item)>>(&self, items: I) -> Vertreap<Item, P> {
+    let old_version = self.state.version.get();
+    let new_version = old_version + 1;
+    assert!(new_version != 0);
+    self.state.version.set(new_version);
+    assert!(self.version < new_version);
+    let mut root = self.root.clone();
+    let mut count = self.count;
+    for (priority, item) in items {
+      let (new_root, new_ct) = match root {
+        None => (VertreapNode::leaf(new_version, priority, item), 1),
+        Some(ref root_node) => root_node._append_batch(new_version, priority, item),
+      };
+      root = Some(Rc::new(new_root));
+      count += new_ct;
+    }
+    Vertreap{
+      version:    new_version,
+      count,
+      state:      self.state.clone(),
+      root,
+    }
+  }
+}
+
+impl<Item, P> Vertreap<Item, P>
+where Item: PartialOrd,
+      P: Copy + Ord,
+{
+  /// Build a treap in O(n) from `items`, which must already be sorted in
+  /// ascending order, each paired with its priority. Uses a fresh,
+  /// unshared version counter starting at version 1. Adjacent equal
+  /// items are merged, keeping the first priority and the last item,
+  /// matching `append`'s last-wins semantics.
+  pub fn from_sorted_iter<I: IntoIterator<Item=(P, Item)>>(items: I) -> Vertreap<Item, P> {
+    let (root, count) = _build_sorted(1, items);
+    Vertreap{
+      version:    1,
+      count,
+      state:      Rc::new(VertreapState{version: Cell::new(1)}),
+      root,
+    }
+  }
+}
+
+impl<K, V, P> Vertreap<KV<K, V>, P> {
+  /// Find the key-value pair matching `key`, ordered by the explicit
+  /// comparator `cmp` rather than `K: Ord`.
+  pub fn find_by<C>(&self, key: &K, cmp: &C) -> Option<Rc<KV<K, V>>> where C: Fn(&K, &K) -> Ordering {
+    match self.root {
+      None => None,
+      Some(ref root_node) => root_node._find_by(self.version, key, cmp),
+    }
+  }
+}
+
+impl<K, V, P> Vertreap<KV<K, V>, P>
+where P: Copy + Ord,
+{
+  /// Insert `key`/`val`, ordered by the explicit comparator `cmp` rather
+  /// than `K: Ord`.
+  pub fn append_with_priority_by<C>(&self, priority: P, key: K, val: V, cmp: &C) -> Vertreap<KV<K, V>, P>
+  where C: Fn(&K, &K) -> Ordering,
+  {
+    let old_version = self.state.version.get();
+    let new_version = old_version + 1;
+    assert!(new_version != 0);
+    self.state.version.set(new_version);
+    assert!(self.version < new_version);
+    let (new_root, new_ct) = match self.root {
+      None => (VertreapNode::leaf(new_version, priority, KV{k: key, v: val}), 1),
+      Some(ref root_node) => root_node._append_by(new_version, priority, key, val, cmp),
+    };
+    Vertreap{
+      version:    new_version,
+      count:      self.count + new_ct,
+      state:      self.state.clone(),
+      root:       Some(Rc::new(new_root)),
+    }
+  }
 }
 
 pub struct VertreapNode<Item, P> {
   version:  u64,
   priority: P,
+  size:     usize,
   item:     Rc<Item>,
   left:     Option<Rc<VertreapNode<Item, P>>>,
   right:    Option<Rc<VertreapNode<Item, P>>>,
 }
 
+fn _node_size<Item, P>(node: &Option<Rc<VertreapNode<Item, P>>>) -> usize {
+  match node {
+    None => 0,
+    Some(n) => n.size,
+  }
+}
+
 impl<Item, P> VertreapNode<Item, P> {
   fn leaf(version: u64, priority: P, item: Item) -> VertreapNode<Item, P> {
     VertreapNode{
       version,
       priority,
+      size:     1,
       item:     Rc::new(item),
       left:     None,
       right:    None,
@@ -448,9 +1000,11 @@ impl<Item, P> VertreapNode<Item, P> {
     if let Some(ref right_node) = right {
       assert!(right_node.version <= version);
     }
+    let size = 1 + _node_size(&left) + _node_size(&right);
     VertreapNode{
       version,
       priority,
+      size,
       item,
       left,
       right,
@@ -480,6 +1034,106 @@ impl<Item, P> VertreapNode<Item, P> {
       }
     }
   }
+
+  fn _select(&self, version: u64, idx: usize) -> Option<Rc<Item>> {
+    assert!(self.version <= version);
+    let left_size = _node_size(&self.left);
+    if idx < left_size {
+      match self.left {
+        None => None,
+        Some(ref l_node) => l_node._select(version, idx),
+      }
+    } else if idx == left_size {
+      Some(self.item.clone())
+    } else {
+      match self.right {
+        None => None,
+        Some(ref r_node) => r_node._select(version, idx - left_size - 1),
+      }
+    }
+  }
+
+  fn _rank<K>(&self, version: u64, key: &K) -> usize where Item: PartialOrd<K> {
+    assert!(self.version <= version);
+    match self.item.partial_cmp(key) {
+      None => panic!(),
+      Some(Ordering::Equal) | Some(Ordering::Greater) => {
+        match self.left {
+          None => 0,
+          Some(ref l_node) => l_node._rank(version, key),
+        }
+      }
+      Some(Ordering::Less) => {
+        let left_size = _node_size(&self.left);
+        match self.right {
+          None => left_size + 1,
+          Some(ref r_node) => left_size + 1 + r_node._rank(version, key),
+        }
+      }
+    }
+  }
+}
+
+impl<K, V, P> VertreapNode<KV<K, V>, P> {
+  fn _find_by<C>(&self, version: u64, key: &K, cmp: &C) -> Option<Rc<KV<K, V>>> where C: Fn(&K, &K) -> Ordering {
+    assert!(self.version <= version);
+    match cmp(&self.item.k, key) {
+      Ordering::Equal => {
+        Some(self.item.clone())
+      }
+      Ordering::Greater => {
+        match self.left {
+          None => None,
+          Some(ref l_node) => l_node._find_by(version, key, cmp),
+        }
+      }
+      Ordering::Less => {
+        match self.right {
+          None => None,
+          Some(ref r_node) => r_node._find_by(version, key, cmp),
+        }
+      }
+    }
+  }
+}
+
+impl<K, V, P> VertreapNode<KV<K, V>, P> where P: Copy + Ord {
+  fn _append_by<C>(&self, new_version: u64, new_priority: P, new_key: K, new_val: V, cmp: &C) -> (VertreapNode<KV<K, V>, P>, usize)
+  where C: Fn(&K, &K) -> Ordering,
+  {
+    assert!(self.version < new_version);
+    match cmp(&new_key, &self.item.k) {
+      Ordering::Equal => {
+        (VertreapNode::branch(new_version, self.priority, Rc::new(KV{k: new_key, v: new_val}), self.left.clone(), self.right.clone()), 0)
+      }
+      Ordering::Less => {
+        let (new_left, new_ct) = match self.left {
+          None => (VertreapNode::leaf(new_version, new_priority, KV{k: new_key, v: new_val}), 1),
+          Some(ref l_node) => l_node._append_by(new_version, new_priority, new_key, new_val, cmp),
+        };
+        let heap_ordered = new_left.priority <= self.priority;
+        let tmp_node = VertreapNode::branch(new_version, self.priority, self.item.clone(), Some(Rc::new(new_left)), self.right.clone());
+        if heap_ordered {
+          (tmp_node, new_ct)
+        } else {
+          (tmp_node._rotate_right(new_version), new_ct)
+        }
+      }
+      Ordering::Greater => {
+        let (new_right, new_ct) = match self.right {
+          None => (VertreapNode::leaf(new_version, new_priority, KV{k: new_key, v: new_val}), 1),
+          Some(ref r_node) => r_node._append_by(new_version, new_priority, new_key, new_val, cmp),
+        };
+        let heap_ordered = new_right.priority <= self.priority;
+        let tmp_node = VertreapNode::branch(new_version, self.priority, self.item.clone(), self.left.clone(), Some(Rc::new(new_right)));
+        if heap_ordered {
+          (tmp_node, new_ct)
+        } else {
+          (tmp_node._rotate_left(new_version), new_ct)
+        }
+      }
+    }
+  }
 }
 
 impl<Item, P> VertreapNode<Item, P> where P: Copy {
@@ -566,4 +1220,321 @@ impl<Item, P> VertreapNode<Item, P> where Item: PartialOrd, P: Copy + Ord {
       }
     }
   }
+
+  /// Like `_append`, but usable for a run of insertions that all share
+  /// the same `new_version` stamp (`_append` requires a strictly older
+  /// `self`, which no longer holds once earlier items in the run have
+  /// already been stamped with `new_version`).
+  fn _append_batch(&self, new_version: u64, new_priority: P, new_item: Item) -> (VertreapNode<Item, P>, usize) {
+    assert!(self.version <= new_version);
+    match new_item.partial_cmp(&*self.item) {
+      None => panic!(),
+      Some(Ordering::Equal) => {
+        (VertreapNode::branch(new_version, self.priority, Rc::new(new_item), self.left.clone(), self.right.clone()), 0)
+      }
+      Some(Ordering::Less) => {
+        let (new_left, new_ct) = match self.left {
+          None => (VertreapNode::leaf(new_version, new_priority, new_item), 1),
+          Some(ref l_node) => l_node._append_batch(new_version, new_priority, new_item),
+        };
+        let heap_ordered = new_left.priority <= self.priority;
+        let tmp_node = VertreapNode::branch(new_version, self.priority, self.item.clone(), Some(Rc::new(new_left)), self.right.clone());
+        if heap_ordered {
+          (tmp_node, new_ct)
+        } else {
+          (tmp_node._rotate_right(new_version), new_ct)
+        }
+      }
+      Some(Ordering::Greater) => {
+        let (new_right, new_ct) = match self.right {
+          None => (VertreapNode::leaf(new_version, new_priority, new_item), 1),
+          Some(ref r_node) => r_node._append_batch(new_version, new_priority, new_item),
+        };
+        let heap_ordered = new_right.priority <= self.priority;
+        let tmp_node = VertreapNode::branch(new_version, self.priority, self.item.clone(), self.left.clone(), Some(Rc::new(new_right)));
+        if heap_ordered {
+          (tmp_node, new_ct)
+        } else {
+          (tmp_node._rotate_left(new_version), new_ct)
+        }
+      }
+    }
+  }
+
+  fn _remove<K>(&self, new_version: u64, key: &K) -> (Option<VertreapNode<Item, P>>, usize) where Item: PartialOrd<K> {
+    assert!(self.version < new_version);
+    match (*self.item).partial_cmp(key) {
+      None => panic!(),
+      Some(Ordering::Equal) => {
+        (self._delete_root(new_version), 1)
+      }
+      Some(Ordering::Greater) => {
+        match self.left {
+          None => (Some(self._rebuild(new_version)), 0),
+          Some(ref l_node) => {
+            let (new_left, ct) = l_node._remove(new_version, key);
+            (Some(VertreapNode::branch(new_version, self.priority, self.item.clone(), new_left.map(Rc::new), self.right.clone())), ct)
+          }
+        }
+      }
+      Some(Ordering::Less) => {
+        match self.right {
+          None => (Some(self._rebuild(new_version)), 0),
+          Some(ref r_node) => {
+            let (new_right, ct) = r_node._remove(new_version, key);
+            (Some(VertreapNode::branch(new_version, self.priority, self.item.clone(), self.left.clone(), new_right.map(Rc::new))), ct)
+          }
+        }
+      }
+    }
+  }
+
+  /// Rotate `self` down the path of whichever child has the larger
+  /// priority, splicing it out once it becomes a leaf or has a single
+  /// remaining child.
+  fn _delete_root(&self, new_version: u64) -> Option<VertreapNode<Item, P>> {
+    match (&self.left, &self.right) {
+      (None, None) => None,
+      (Some(l_node), None) => Some(l_node._shallow_clone()),
+      (None, Some(r_node)) => Some(r_node._shallow_clone()),
+      (Some(l_node), Some(r_node)) => {
+        if l_node.priority >= r_node.priority {
+          let rotated = self._rotate_right(new_version);
+          match rotated.right {
+            Some(ref moved_node) => {
+              let spliced = moved_node._delete_root(new_version);
+              Some(VertreapNode::branch(new_version, rotated.priority, rotated.item.clone(), rotated.left.clone(), spliced.map(Rc::new)))
+            }
+            None => panic!(),
+          }
+        } else {
+          let rotated = self._rotate_left(new_version);
+          match rotated.left {
+            Some(ref moved_node) => {
+              let spliced = moved_node._delete_root(new_version);
+              Some(VertreapNode::branch(new_version, rotated.priority, rotated.item.clone(), spliced.map(Rc::new), rotated.right.clone()))
+            }
+            None => panic!(),
+          }
+        }
+      }
+    }
+  }
+
+  fn _rebuild(&self, new_version: u64) -> VertreapNode<Item, P> {
+    VertreapNode::branch(new_version, self.priority, self.item.clone(), self.left.clone(), self.right.clone())
+  }
+
+  fn _shallow_clone(&self) -> VertreapNode<Item, P> {
+    VertreapNode{
+      version:  self.version,
+      priority: self.priority,
+      size:     self.size,
+      item:     self.item.clone(),
+      left:     self.left.clone(),
+      right:    self.right.clone(),
+    }
+  }
+
+  fn _split<K>(&self, new_version: u64, key: &K) -> (Option<VertreapNode<Item, P>>, Option<(P, Rc<Item>)>, Option<VertreapNode<Item, P>>) where Item: PartialOrd<K> {
+    assert!(self.version < new_version);
+    match (*self.item).partial_cmp(key) {
+      None => panic!(),
+      Some(Ordering::Equal) => {
+        (self.left.as_ref().map(|n| n._shallow_clone()), Some((self.priority, self.item.clone())), self.right.as_ref().map(|n| n._shallow_clone()))
+      }
+      Some(Ordering::Greater) => {
+        match self.left {
+          None => (None, None, Some(self._rebuild(new_version))),
+          Some(ref l_node) => {
+            let (ll, mid, lr) = l_node._split(new_version, key);
+            let new_right = VertreapNode::branch(new_version, self.priority, self.item.clone(), lr.map(Rc::new), self.right.clone());
+            (ll, mid, Some(new_right))
+          }
+        }
+      }
+      Some(Ordering::Less) => {
+        match self.right {
+          None => (Some(self._rebuild(new_version)), None, None),
+          Some(ref r_node) => {
+            let (rl, mid, rr) = r_node._split(new_version, key);
+            let new_left = VertreapNode::branch(new_version, self.priority, self.item.clone(), self.left.clone(), rl.map(Rc::new));
+            (Some(new_left), mid, rr)
+          }
+        }
+      }
+    }
+  }
+}
+
+/// Merge two treaps where every item in `left` precedes every item in
+/// `right`, preserving heap order: the higher-priority root becomes the
+/// new root, with the matching child replaced by the recursive join of
+/// the overlap.
+fn _join_nodes<Item, P>(left: &Option<Rc<VertreapNode<Item, P>>>, right: &Option<Rc<VertreapNode<Item, P>>>, new_version: u64) -> Option<VertreapNode<Item, P>>
+where Item: PartialOrd,
+      P: Copy + Ord,
+{
+  match (left, right) {
+    (None, None) => None,
+    (Some(l_node), None) => Some(l_node._rebuild(new_version)),
+    (None, Some(r_node)) => Some(r_node._rebuild(new_version)),
+    (Some(l_node), Some(r_node)) => {
+      if l_node.priority >= r_node.priority {
+        let new_right = _join_nodes(&l_node.right, right, new_version);
+        Some(VertreapNode::branch(new_version, l_node.priority, l_node.item.clone(), l_node.left.clone(), new_right.map(Rc::new)))
+      } else {
+        let new_left = _join_nodes(left, &r_node.left, new_version);
+        Some(VertreapNode::branch(new_version, r_node.priority, r_node.item.clone(), new_left.map(Rc::new), r_node.right.clone()))
+      }
+    }
+  }
+}
+
+struct _SortedBuildEntry<Item, P> {
+  priority: P,
+  item:     Rc<Item>,
+  left:     Option<Rc<VertreapNode<Item, P>>>,
+}
+
+/// Build a treap in O(n) from `items` already sorted in ascending order,
+/// using a right-spine stack of monotonically decreasing priority.
+/// Adjacent items comparing equal are merged in place (keeping the
+/// earlier priority, taking the later item), so the result preserves
+/// the same key-uniqueness invariant as `append`.
+fn _build_sorted<Item, P, I: IntoIterator<Item=(P, Item)>>(new_version: u64, items: I) -> (Option<Rc<VertreapNode<Item, P>>>, usize)
+where Item: PartialOrd,
+      P: Copy + Ord,
+{
+  let mut stack: Vec<_SortedBuildEntry<Item, P>> = Vec::new();
+  let mut count = 0;
+  for (priority, item) in items {
+    if let Some(top) = stack.last_mut() {
+      match item.partial_cmp(&*top.item) {
+        Some(Ordering::Equal) => {
+          top.item = Rc::new(item);
+          continue;
+        }
+        Some(Ordering::Greater) => {}
+        _ => panic!("_build_sorted requires items in strictly ascending order"),
+      }
+    }
+    count += 1;
+    let mut last_popped = None;
+    while let Some(top) = stack.last() {
+      if top.priority < priority {
+        let popped = stack.pop().unwrap();
+        let node = VertreapNode::branch(new_version, popped.priority, popped.item, popped.left, last_popped);
+        last_popped = Some(Rc::new(node));
+      } else {
+        break;
+      }
+    }
+    stack.push(_SortedBuildEntry{priority, item: Rc::new(item), left: last_popped});
+  }
+  let mut root = None;
+  while let Some(entry) = stack.pop() {
+    let node = VertreapNode::branch(new_version, entry.priority, entry.item, entry.left, root);
+    root = Some(Rc::new(node));
+  }
+  (root, count)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn append_remove_matches_btreemap_oracle() {
+    let mut map = VertreapMap::<i32, i32, u64>::new_with_random_hasher();
+    let mut oracle = BTreeMap::new();
+    for (i, key) in [5, 1, 9, 3, 7, 1, 5, 2, 8, 4, 6, 0].iter().enumerate() {
+      map = map.append(*key, i as i32);
+      oracle.insert(*key, i as i32);
+    }
+    for key in [1, 7, 100, 0, 9] {
+      map = map.remove(&key);
+      oracle.remove(&key);
+    }
+    assert_eq!(map.len(), oracle.len());
+    for (key, val) in oracle.iter() {
+      assert_eq!(map.find(key).map(|kv| kv.v), Some(*val));
+    }
+    for key in [1, 7, 100] {
+      assert!(map.find(&key).is_none());
+    }
+  }
+
+  #[test]
+  fn select_and_rank_match_sorted_order() {
+    let keys = [5, 1, 9, 3, 7, 2, 8, 4, 6, 0];
+    let mut map = VertreapMap::<i32, (), u64>::new_with_random_hasher();
+    for key in keys {
+      map = map.append(key, ());
+    }
+    let mut sorted = keys.to_vec();
+    sorted.sort();
+    for (idx, key) in sorted.iter().enumerate() {
+      assert_eq!(map.select(idx).map(|kv| kv.k), Some(*key));
+      assert_eq!(map.rank(key), idx);
+    }
+    assert!(map.select(sorted.len()).is_none());
+  }
+
+  #[test]
+  fn split_then_join_round_trips() {
+    let mut map = VertreapMap::<i32, i32, u64>::new_with_random_hasher();
+    for key in 0..10 {
+      map = map.append(key, key * 10);
+    }
+    let (left, mid, right) = map.split(&5);
+    assert_eq!(mid.map(|kv| kv.v), Some(50));
+    let rejoined = left.join(&right);
+    assert_eq!(rejoined.len(), map.len() - 1);
+    for key in (0..10).filter(|&k| k != 5) {
+      assert_eq!(rejoined.find(&key).map(|kv| kv.v), Some(key * 10));
+    }
+  }
+
+  #[test]
+  fn versioned_map_reads_past_snapshots_and_gc_keeps_latest() {
+    let m0 = VertreapMap::<i32, i32, u64>::new_with_random_hasher();
+    let registry = VersionedVertreapMap::new(m0.clone());
+    let m1 = m0.append(1, 100);
+    registry.commit(m1.clone());
+    let m2 = m1.append(2, 200);
+    registry.commit(m2.clone());
+
+    assert!(registry.find_at(m0.version(), &1).is_none());
+    assert_eq!(registry.find_at(m1.version(), &1).map(|kv| kv.v), Some(100));
+    assert_eq!(registry.find_at(m2.version(), &2).map(|kv| kv.v), Some(200));
+    assert_eq!(registry.len_at(m1.version()), 1);
+
+    registry.gc(u64::MAX);
+    assert_eq!(registry.latest_version(), m2.version());
+    assert_eq!(registry.find_at(m2.version(), &2).map(|kv| kv.v), Some(200));
+  }
+
+  #[test]
+  fn comparator_map_orders_by_runtime_comparator() {
+    let map = VertreapMapBy::<i32, (), _, u64>::new_with_thread_rng(|a: &i32, b: &i32| b.cmp(a));
+    let map = map.append(1, ()).append(3, ()).append(2, ());
+    assert_eq!(map.iter().map(|kv| kv.k).collect::<Vec<_>>(), vec![3, 2, 1]);
+    assert_eq!(map.find(&2).map(|kv| kv.k), Some(2));
+    assert!(map.find(&5).is_none());
+  }
+
+  #[test]
+  fn from_sorted_iter_merges_duplicate_keys_keeping_last_value() {
+    let map: VertreapMap<i32, &str, u64> = vec![(1, "a"), (1, "b"), (2, "c")].into_iter().collect();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.find(&1).map(|kv| kv.v), Some("b"));
+    assert_eq!(map.find(&2).map(|kv| kv.v), Some("c"));
+
+    let set: VertreapSet<i32, u64> = vec![1, 1, 2, 2, 3].into_iter().collect();
+    assert_eq!(set.len(), 3);
+    assert!(set.contains(&1));
+    assert!(set.contains(&2));
+    assert!(set.contains(&3));
+  }
 }